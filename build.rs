@@ -1,56 +1,147 @@
 extern crate bindgen;
 
 use std::env;
+use std::fs;
 use std::path::PathBuf;
 use std::process::Command;
-use std::fs;
 
-fn main() {
-    // Check if the target OS is macOS
-    if cfg!(target_os = "macos") {
-        // Use Homebrew to find the Opus library
-        let brew_prefix = Command::new("brew")
-            .arg("--prefix")
-            .arg("opus")
-            .output()
-            .expect("Failed to execute brew")
-            .stdout;
-        let brew_prefix = String::from_utf8(brew_prefix).expect("Invalid UTF-8 output from brew");
-        let brew_prefix = brew_prefix.trim();
-
-        println!("Brew prefix: {}", brew_prefix);
-
-        // Set the include path for the Opus headers
-        let include_path = format!("{}/include/opus", brew_prefix);
-        let lib_path = format!("{}/lib", brew_prefix);
-
-        println!("cargo:include={}", include_path);
-        println!("cargo:rustc-link-search=native={}", lib_path);
-
-        // The bindgen::Builder is the main entry point to bindgen, and lets you build up options for the resulting bindings.
-        let bindings = bindgen::Builder::default()
-            // The input header we would like to generate bindings for.
-            .header(format!("{}/include/opus/opus.h", brew_prefix))
-            // Add the include path for the Opus headers
-            .clang_arg(format!("-I{}", include_path))
-            // Finish the builder and generate the bindings.
-            .generate()
-            // Unwrap the Result and panic on failure.
-            .expect("Unable to generate bindings");
-
-        // Write the bindings to the $OUT_DIR/bindings.rs file.
-        let out_path = PathBuf::from(env::var("OUT_DIR").unwrap());
-        bindings
-            .write_to_file(out_path.join("bindings.rs"))
-            .expect("Couldn't write bindings!");
+/// Map a Cargo target arch to the arch component vcpkg uses in its triples
+/// (e.g. `x64-windows-static`, `arm64-osx`).
+fn vcpkg_arch(target_arch: &str) -> &str {
+    match target_arch {
+        "x86_64" => "x64",
+        "aarch64" => "arm64",
+        "x86" => "x86",
+        other => other,
+    }
+}
+
+/// Resolve opus' include dir (the directory directly containing `opus.h`)
+/// and lib dir via `pkg-config`, if it's installed and knows about opus.
+fn pkg_config_probe(lib: &str) -> Option<(String, String)> {
+    let exists = Command::new("pkg-config")
+        .arg("--exists")
+        .arg(lib)
+        .status()
+        .ok()?;
+    if !exists.success() {
+        return None;
+    }
+
+    let cflags = Command::new("pkg-config")
+        .arg("--cflags-only-I")
+        .arg(lib)
+        .output()
+        .ok()?;
+    let cflags = String::from_utf8(cflags.stdout).ok()?;
+    let include_dir = cflags
+        .split_whitespace()
+        .next()?
+        .strip_prefix("-I")?
+        .to_string();
+
+    let lib_dir = Command::new("pkg-config")
+        .arg("--variable=libdir")
+        .arg(lib)
+        .output()
+        .ok()?;
+    let lib_dir = String::from_utf8(lib_dir.stdout).ok()?.trim().to_string();
+
+    Some((include_dir, lib_dir))
+}
+
+/// Resolve opus via a vcpkg install, keyed off `VCPKG_ROOT` and a
+/// triple derived from the target OS/arch (e.g. `x64-windows-static`,
+/// `arm64-osx`) rather than a hardcoded one.
+fn vcpkg_probe(target_os: &str, target_arch: &str) -> Option<(String, String)> {
+    let vcpkg_root = env::var("VCPKG_ROOT").ok()?;
+    let arch = vcpkg_arch(target_arch);
+    let triple = match target_os {
+        "windows" => format!("{}-windows-static", arch),
+        "macos" => format!("{}-osx", arch),
+        _ => return None,
+    };
+
+    let installed = PathBuf::from(vcpkg_root).join("installed").join(triple);
+    let include_root = installed.join("include");
+    let lib_dir = installed.join("lib");
+
+    let include_dir = if include_root.join("opus").join("opus.h").exists() {
+        include_root.join("opus")
+    } else if include_root.join("opus.h").exists() {
+        include_root
+    } else {
+        return None;
+    };
+
+    Some((
+        include_dir.to_string_lossy().into_owned(),
+        lib_dir.to_string_lossy().into_owned(),
+    ))
+}
+
+/// Resolve opus via Homebrew. Only used as a macOS fallback when pkg-config
+/// doesn't know about opus.
+fn brew_probe() -> Option<(String, String)> {
+    let output = Command::new("brew")
+        .arg("--prefix")
+        .arg("opus")
+        .output()
+        .ok()?;
+    if !output.status.success() {
+        return None;
     }
+    let prefix = String::from_utf8(output.stdout).ok()?.trim().to_string();
+    Some((
+        format!("{}/include/opus", prefix),
+        format!("{}/lib", prefix),
+    ))
+}
+
+fn main() {
+    let target_os = env::var("CARGO_CFG_TARGET_OS").expect("CARGO_CFG_TARGET_OS not set");
+    let target_arch = env::var("CARGO_CFG_TARGET_ARCH").expect("CARGO_CFG_TARGET_ARCH not set");
+
+    let (include_dir, lib_dir) = match target_os.as_str() {
+        "linux" => pkg_config_probe("opus").expect(
+            "could not find libopus via pkg-config; install libopus-dev (or the opus.pc file)",
+        ),
+        "windows" => vcpkg_probe(&target_os, &target_arch).expect(
+            "could not find libopus via vcpkg; set VCPKG_ROOT and run `vcpkg install opus:x64-windows-static`",
+        ),
+        "macos" => pkg_config_probe("opus")
+            .or_else(|| vcpkg_probe(&target_os, &target_arch))
+            .or_else(brew_probe)
+            .expect("could not find libopus via pkg-config, vcpkg, or Homebrew"),
+        other => panic!("don't know how to locate libopus on target os `{}`", other),
+    };
 
-    // Tell cargo to tell rustc to link the system opus shared library.
+    println!("cargo:include={}", include_dir);
+    println!("cargo:rustc-link-search=native={}", lib_dir);
     println!("cargo:rustc-link-lib=opus");
 
+    let header_path = format!("{}/opus.h", include_dir);
+
+    // The bindgen::Builder is the main entry point to bindgen, and lets you build up options for the resulting bindings.
+    let bindings = bindgen::Builder::default()
+        // The input header we would like to generate bindings for.
+        .header(&header_path)
+        // Add the include path for the Opus headers
+        .clang_arg(format!("-I{}", include_dir))
+        // Finish the builder and generate the bindings.
+        .generate()
+        // Unwrap the Result and panic on failure.
+        .expect("Unable to generate bindings");
+
+    // Write the bindings to the $OUT_DIR/bindings.rs file.
+    let out_path = PathBuf::from(env::var("OUT_DIR").unwrap());
+    bindings
+        .write_to_file(out_path.join("bindings.rs"))
+        .expect("Couldn't write bindings!");
+
     println!("OUT_DIR: {}", env::var("OUT_DIR").unwrap());
 
     // Write the OUT_DIR to a file
     let out_dir = env::var("OUT_DIR").unwrap();
     fs::write("out_dir.txt", &out_dir).expect("Unable to write OUT_DIR to file");
-} 
\ No newline at end of file
+}
@@ -0,0 +1,92 @@
+//! A small PCM accumulator sitting between Opus decode and cpal playback.
+//!
+//! Opus produces fixed-size frames (e.g. 960 samples for a 20ms frame at
+//! 48kHz) but cpal's output callback asks for whatever buffer size the
+//! device negotiated, which rarely lines up. `JitterBuffer` queues decoded
+//! frames and lets the callback drain exactly the number of samples it
+//! needs, regardless of how that cuts across frame boundaries.
+
+/// Queue of decoded PCM frames plus a cursor into the first one, so partial
+/// frames can be consumed without copying the remainder back out.
+#[derive(Debug, Default)]
+pub struct JitterBuffer {
+    frames: Vec<Vec<f32>>,
+    cursor: usize,
+}
+
+impl JitterBuffer {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Push a newly decoded frame onto the back of the queue.
+    pub fn produce(&mut self, frame: Vec<f32>) {
+        if !frame.is_empty() {
+            self.frames.push(frame);
+        }
+    }
+
+    /// Total number of buffered samples not yet consumed.
+    pub fn samples_available(&self) -> usize {
+        self.frames.iter().map(Vec::len).sum::<usize>() - self.cursor
+    }
+
+    /// Fill `out` entirely from buffered frames, popping exhausted frames off
+    /// the front as it goes. On a genuine underrun, fills `out` with silence
+    /// and returns `false`; otherwise returns `true`.
+    pub fn consume_exact(&mut self, out: &mut [f32]) -> bool {
+        if self.samples_available() < out.len() {
+            out.fill(0.0);
+            return false;
+        }
+
+        for sample in out.iter_mut() {
+            while self.cursor >= self.frames[0].len() {
+                self.frames.remove(0);
+                self.cursor = 0;
+            }
+            *sample = self.frames[0][self.cursor];
+            self.cursor += 1;
+        }
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn consume_exact_crosses_frame_boundary() {
+        let mut buffer = JitterBuffer::new();
+        buffer.produce(vec![1.0, 2.0, 3.0]);
+        buffer.produce(vec![4.0, 5.0, 6.0]);
+
+        let mut out = [0.0; 4];
+        assert!(buffer.consume_exact(&mut out));
+        assert_eq!(out, [1.0, 2.0, 3.0, 4.0]);
+        assert_eq!(buffer.samples_available(), 2);
+
+        let mut out = [0.0; 2];
+        assert!(buffer.consume_exact(&mut out));
+        assert_eq!(out, [5.0, 6.0]);
+        assert_eq!(buffer.samples_available(), 0);
+    }
+
+    #[test]
+    fn consume_exact_on_underrun_fills_silence_and_returns_false() {
+        let mut buffer = JitterBuffer::new();
+        buffer.produce(vec![1.0, 2.0]);
+
+        let mut out = [9.0; 4];
+        assert!(!buffer.consume_exact(&mut out));
+        assert_eq!(out, [0.0; 4]);
+    }
+
+    #[test]
+    fn produce_ignores_empty_frames() {
+        let mut buffer = JitterBuffer::new();
+        buffer.produce(Vec::new());
+        assert_eq!(buffer.samples_available(), 0);
+    }
+}
@@ -19,8 +19,14 @@ use tui::style::{Color, Style};
 use tui::widgets::{Axis, Block, Borders, Chart, Dataset, Paragraph};
 use tui::Terminal;
 mod error;
+mod jitter_buffer;
+mod resampler;
+mod transport;
 use cpal::Stream;
 use error::AudioError;
+use jitter_buffer::JitterBuffer;
+use resampler::Resampler;
+use transport::TransportMode;
 
 // Include the generated bindings
 // you need to enable vscode rust-analyzer.cargo.runBuildScripts to run this
@@ -29,6 +35,143 @@ include!(concat!(env!("OUT_DIR"), "/bindings.rs"));
 // Define a struct to encapsulate the Opus encoder
 
 const MAX_PACKET_SIZE: usize = 1275; // Maximum size of an Opus packet for 48kHz stereo
+
+/// The rate Opus actually encodes/decodes at. Must be one of Opus' supported
+/// rates (8000, 12000, 16000, 24000, 48000); lower rates trade quality for
+/// bandwidth. Device capture/playback happens at whatever rate the hardware
+/// negotiated and is resampled to/from this rate at the edges.
+const OPUS_SAMPLE_RATE: i32 = 48000;
+
+/// 20ms frame size, derived strictly from `OPUS_SAMPLE_RATE` rather than the
+/// device's rate.
+const OPUS_FRAME_SIZE: i32 = OPUS_SAMPLE_RATE / 50;
+
+/// Signal type hint passed to `OPUS_SET_SIGNAL`, letting the encoder bias its
+/// internal heuristics toward speech or music instead of auto-detecting.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum OpusSignal {
+    Auto,
+    Voice,
+    Music,
+}
+
+impl OpusSignal {
+    fn as_ctl_value(self) -> i32 {
+        match self {
+            OpusSignal::Auto => OPUS_AUTO,
+            OpusSignal::Voice => OPUS_SIGNAL_VOICE as i32,
+            OpusSignal::Music => OPUS_SIGNAL_MUSIC as i32,
+        }
+    }
+}
+
+/// Tunable Opus encoder parameters, applied via `opus_encoder_ctl` once the
+/// encoder has been created. Any field left `None` keeps the encoder's
+/// default for that setting.
+#[derive(Debug, Clone, Copy, Default)]
+struct OpusEncoderConfig {
+    bitrate: Option<i32>,
+    vbr: Option<bool>,
+    vbr_constraint: Option<bool>,
+    complexity: Option<i32>,
+    signal: Option<OpusSignal>,
+    inband_fec: Option<bool>,
+    packet_loss_perc: Option<i32>,
+}
+
+impl OpusEncoderConfig {
+    fn new() -> Self {
+        Self::default()
+    }
+
+    fn with_bitrate(mut self, bitrate: i32) -> Self {
+        self.bitrate = Some(bitrate);
+        self
+    }
+
+    fn with_vbr(mut self, vbr: bool) -> Self {
+        self.vbr = Some(vbr);
+        self
+    }
+
+    fn with_vbr_constraint(mut self, constrained: bool) -> Self {
+        self.vbr_constraint = Some(constrained);
+        self
+    }
+
+    fn with_complexity(mut self, complexity: i32) -> Self {
+        self.complexity = Some(complexity);
+        self
+    }
+
+    fn with_signal(mut self, signal: OpusSignal) -> Self {
+        self.signal = Some(signal);
+        self
+    }
+
+    fn with_inband_fec(mut self, enabled: bool) -> Self {
+        self.inband_fec = Some(enabled);
+        self
+    }
+
+    fn with_packet_loss_perc(mut self, percent: i32) -> Self {
+        self.packet_loss_perc = Some(percent);
+        self
+    }
+
+    /// Parse `--bitrate <bps>`, `--vbr`, `--vbr-constrained`, `--complexity
+    /// <0-10>`, and `--signal <auto|voice|music>` from the process'
+    /// command-line arguments, so quality/bandwidth can be tuned at runtime
+    /// instead of recompiling. Any flag not passed leaves that field at the
+    /// encoder's default.
+    fn from_env_args() -> Self {
+        let args: Vec<String> = std::env::args().collect();
+        let mut config = Self::new();
+
+        if let Some(bitrate) = args
+            .iter()
+            .position(|a| a == "--bitrate")
+            .and_then(|i| args.get(i + 1))
+            .and_then(|v| v.parse().ok())
+        {
+            config = config.with_bitrate(bitrate);
+        }
+
+        if args.iter().any(|a| a == "--vbr") {
+            config = config.with_vbr(true);
+        }
+
+        if args.iter().any(|a| a == "--vbr-constrained") {
+            config = config.with_vbr_constraint(true);
+        }
+
+        if let Some(complexity) = args
+            .iter()
+            .position(|a| a == "--complexity")
+            .and_then(|i| args.get(i + 1))
+            .and_then(|v| v.parse().ok())
+        {
+            config = config.with_complexity(complexity);
+        }
+
+        if let Some(signal) = args
+            .iter()
+            .position(|a| a == "--signal")
+            .and_then(|i| args.get(i + 1))
+            .and_then(|v| match v.as_str() {
+                "auto" => Some(OpusSignal::Auto),
+                "voice" => Some(OpusSignal::Voice),
+                "music" => Some(OpusSignal::Music),
+                _ => None,
+            })
+        {
+            config = config.with_signal(signal);
+        }
+
+        config
+    }
+}
+
 struct SafeOpusEncoder {
     encoder: *mut OpusEncoder,
 }
@@ -55,6 +198,71 @@ impl SafeOpusEncoder {
         Ok(SafeOpusEncoder { encoder })
     }
 
+    /// Apply a set of CTL-backed tuning parameters to this encoder. Only the
+    /// fields present in `config` are touched; the rest keep their current
+    /// value.
+    fn configure(&self, config: &OpusEncoderConfig) -> Result<()> {
+        if let Some(bitrate) = config.bitrate {
+            self.ctl_set(OPUS_SET_BITRATE_REQUEST as i32, bitrate)?;
+        }
+        if let Some(vbr) = config.vbr {
+            self.ctl_set(OPUS_SET_VBR_REQUEST as i32, vbr as i32)?;
+        }
+        if let Some(constrained) = config.vbr_constraint {
+            self.ctl_set(OPUS_SET_VBR_CONSTRAINT_REQUEST as i32, constrained as i32)?;
+        }
+        if let Some(complexity) = config.complexity {
+            self.ctl_set(OPUS_SET_COMPLEXITY_REQUEST as i32, complexity)?;
+        }
+        if let Some(signal) = config.signal {
+            self.ctl_set(OPUS_SET_SIGNAL_REQUEST as i32, signal.as_ctl_value())?;
+        }
+        if let Some(enabled) = config.inband_fec {
+            self.ctl_set(OPUS_SET_INBAND_FEC_REQUEST as i32, enabled as i32)?;
+        }
+        if let Some(percent) = config.packet_loss_perc {
+            self.ctl_set(OPUS_SET_PACKET_LOSS_PERC_REQUEST as i32, percent)?;
+        }
+        Ok(())
+    }
+
+    fn ctl_set(&self, request: i32, value: i32) -> Result<()> {
+        let result = unsafe { opus_encoder_ctl(self.encoder, request, value) };
+        if result < 0 {
+            return Err(AudioError::OpusCtlError(result)).context("Failed to set encoder CTL");
+        }
+        Ok(())
+    }
+
+    fn ctl_get(&self, request: i32) -> Result<i32> {
+        let mut value: i32 = 0;
+        let result = unsafe { opus_encoder_ctl(self.encoder, request, &mut value as *mut i32) };
+        if result < 0 {
+            return Err(AudioError::OpusCtlError(result)).context("Failed to get encoder CTL");
+        }
+        Ok(value)
+    }
+
+    fn bitrate(&self) -> Result<i32> {
+        self.ctl_get(OPUS_GET_BITRATE_REQUEST as i32)
+    }
+
+    fn vbr(&self) -> Result<bool> {
+        Ok(self.ctl_get(OPUS_GET_VBR_REQUEST as i32)? != 0)
+    }
+
+    fn complexity(&self) -> Result<i32> {
+        self.ctl_get(OPUS_GET_COMPLEXITY_REQUEST as i32)
+    }
+
+    fn inband_fec(&self) -> Result<bool> {
+        Ok(self.ctl_get(OPUS_GET_INBAND_FEC_REQUEST as i32)? != 0)
+    }
+
+    fn packet_loss_perc(&self) -> Result<i32> {
+        self.ctl_get(OPUS_GET_PACKET_LOSS_PERC_REQUEST as i32)
+    }
+
     fn encode(&self, pcm_data: &[i16], opus_buffer: &mut [u8], frame_size: i32) -> Result<i32> {
         let result = unsafe {
             opus_encode(
@@ -71,7 +279,12 @@ impl SafeOpusEncoder {
         }
         Ok(result)
     }
-    fn encode_float(&self, pcm_data: &[f32], opus_buffer: &mut [u8], frame_size: i32) -> Result<i32> {
+    fn encode_float(
+        &self,
+        pcm_data: &[f32],
+        opus_buffer: &mut [u8],
+        frame_size: i32,
+    ) -> Result<i32> {
         let result = unsafe {
             opus_encode_float(
                 self.encoder,
@@ -135,7 +348,17 @@ impl SafeOpusDecoder {
         Ok(result)
     }
 
-    fn decode_float(&self, opus_buffer: &[u8], pcm_out: &mut [f32], frame_size: i32) -> Result<i32> {
+    /// Decode a packet, optionally asking Opus to reconstruct the *previous*
+    /// frame from this packet's inband FEC data instead of the frame this
+    /// packet actually carries. Used to recover a frame that never arrived
+    /// when the encoder that produced it had FEC enabled.
+    fn decode_float(
+        &self,
+        opus_buffer: &[u8],
+        pcm_out: &mut [f32],
+        frame_size: i32,
+        decode_fec: bool,
+    ) -> Result<i32> {
         let result = unsafe {
             opus_decode_float(
                 self.decoder,
@@ -143,7 +366,7 @@ impl SafeOpusDecoder {
                 opus_buffer.len() as i32,
                 pcm_out.as_mut_ptr(),
                 frame_size,
-                0,
+                decode_fec as i32,
             )
         };
 
@@ -152,6 +375,27 @@ impl SafeOpusDecoder {
         }
         Ok(result)
     }
+
+    /// Packet-loss concealment: synthesize a plausible replacement frame when
+    /// a packet was lost outright and no FEC data is available to recover it.
+    fn decode_float_plc(&self, pcm_out: &mut [f32], frame_size: i32) -> Result<i32> {
+        let result = unsafe {
+            opus_decode_float(
+                self.decoder,
+                std::ptr::null(),
+                0,
+                pcm_out.as_mut_ptr(),
+                frame_size,
+                0,
+            )
+        };
+
+        if result < 0 {
+            return Err(AudioError::OpusDecodeError(result))
+                .context("Failed to conceal lost packet");
+        }
+        Ok(result)
+    }
 }
 
 impl Drop for SafeOpusDecoder {
@@ -162,7 +406,15 @@ impl Drop for SafeOpusDecoder {
     }
 }
 
-fn setup_host() -> Result<(cpal::Device, cpal::Device, cpal::StreamConfig, i32)> {
+/// A device plus the config and sample format cpal actually negotiated for
+/// it. Capture/playback must honor `sample_format` instead of assuming f32.
+struct DeviceSetup {
+    device: cpal::Device,
+    config: cpal::StreamConfig,
+    sample_format: cpal::SampleFormat,
+}
+
+fn setup_host() -> Result<(DeviceSetup, DeviceSetup)> {
     let host = cpal::default_host();
 
     let input_device = host
@@ -173,30 +425,114 @@ fn setup_host() -> Result<(cpal::Device, cpal::Device, cpal::StreamConfig, i32)>
         .default_output_device()
         .ok_or_else(|| AudioError::NoDevice("No output device found".into()))?;
 
-    let config = input_device
+    let input_config = input_device
         .default_input_config()
         .map_err(|e| AudioError::StreamConfigError(e.to_string()))?;
-    
-    // Calculate frame size based on sample rate (20ms frame size)
-    let frame_size = (config.sample_rate().0 as f32 * 0.02) as i32;
 
-    Ok((input_device, output_device, config.into(), frame_size))
+    let output_config = output_device
+        .default_output_config()
+        .map_err(|e| AudioError::StreamConfigError(e.to_string()))?;
+
+    let input = DeviceSetup {
+        device: input_device,
+        sample_format: input_config.sample_format(),
+        config: input_config.into(),
+    };
+    let output = DeviceSetup {
+        device: output_device,
+        sample_format: output_config.sample_format(),
+        config: output_config.into(),
+    };
+
+    Ok((input, output))
 }
 
 fn err_fn(err: cpal::StreamError) {
     eprintln!("an error occurred on stream: {}", err);
 }
 
-fn audio_input(running: Arc<AtomicBool>, tx: Sender<Vec<f32>>) -> Result<()> {
-    let (input_device, _output_device, config, _frame_size) = setup_host()?;
+/// Build the capture stream for a concrete sample type `T`, converting each
+/// captured buffer to `f32` (via `to_f32`) before resampling, then
+/// accumulating the resampled samples and only forwarding exact
+/// `frame_size`-sample frames to `tx`. `encode_audio` hands whatever it
+/// receives straight to `opus_encode_float` together with a fixed
+/// `frame_size`, so a short or empty buffer here would be an out-of-bounds
+/// read on the other side of that FFI call.
+fn build_typed_input_stream<T>(
+    device: &cpal::Device,
+    config: &cpal::StreamConfig,
+    mut resampler: Resampler,
+    tx: Sender<Vec<f32>>,
+    to_f32: fn(T) -> f32,
+    frame_size: usize,
+) -> Result<Stream>
+where
+    T: cpal::SizedSample + Send + 'static,
+{
+    let mut accumulator: Vec<f32> = Vec::with_capacity(frame_size);
+
+    let input_data_fn = move |data: &[T], _: &cpal::InputCallbackInfo| {
+        let floats: Vec<f32> = data.iter().copied().map(to_f32).collect();
+        accumulator.extend(resampler.process(&floats));
+
+        while accumulator.len() >= frame_size {
+            let frame: Vec<f32> = accumulator.drain(..frame_size).collect();
+            match tx.try_send(frame) {
+                Ok(_) => (),
+                Err(e) => eprintln!("Error audio_input: {:?}", e),
+            }
+        }
+    };
 
-    let input_data_fn =
-        move |data: &[f32], _: &cpal::InputCallbackInfo| match tx.try_send(data.to_vec()) {
-            Ok(_) => (),
-            Err(e) => eprintln!("Error audio_input: {:?}", e),
-        };
+    Ok(device.build_input_stream(config, input_data_fn, err_fn, None)?)
+}
 
-    let stream = input_device.build_input_stream(&config, input_data_fn, err_fn, None)?;
+fn audio_input(running: Arc<AtomicBool>, tx: Sender<Vec<f32>>, frame_size: i32) -> Result<()> {
+    let (input, _output) = setup_host()?;
+    let resampler = Resampler::new(input.config.sample_rate.0, OPUS_SAMPLE_RATE as u32);
+    let frame_size = frame_size as usize;
+
+    let stream = match input.sample_format {
+        cpal::SampleFormat::I8 => build_typed_input_stream::<i8>(
+            &input.device,
+            &input.config,
+            resampler,
+            tx,
+            |s| s as f32 / i8::MAX as f32,
+            frame_size,
+        )?,
+        cpal::SampleFormat::I16 => build_typed_input_stream::<i16>(
+            &input.device,
+            &input.config,
+            resampler,
+            tx,
+            |s| s as f32 / i16::MAX as f32,
+            frame_size,
+        )?,
+        cpal::SampleFormat::I32 => build_typed_input_stream::<i32>(
+            &input.device,
+            &input.config,
+            resampler,
+            tx,
+            |s| s as f32 / i32::MAX as f32,
+            frame_size,
+        )?,
+        cpal::SampleFormat::F32 => build_typed_input_stream::<f32>(
+            &input.device,
+            &input.config,
+            resampler,
+            tx,
+            |s| s,
+            frame_size,
+        )?,
+        other => {
+            return Err(AudioError::StreamConfigError(format!(
+                "unsupported input sample format: {:?}",
+                other
+            ))
+            .into())
+        }
+    };
     stream.play()?;
 
     while running.load(Ordering::Relaxed) {
@@ -206,23 +542,98 @@ fn audio_input(running: Arc<AtomicBool>, tx: Sender<Vec<f32>>) -> Result<()> {
     Ok(())
 }
 
-fn audio_output(running: Arc<AtomicBool>, rx: Receiver<Vec<f32>>) -> Result<()> {
-    let (_input_device, output_device, config, _frame_size) = setup_host()?;
-
-    let output_data_fn = move |output: &mut [f32], _: &cpal::OutputCallbackInfo| {
-        match rx.try_recv() {
-            Ok(val) => match val {
-                Some(data) => {
-                    for (i, sample) in output.iter_mut().enumerate().take(data.len()) {
-                        *sample = data[i];
-                    }
+/// Build the playback stream for a concrete sample type `T`, draining
+/// decoded frames into a jitter buffer and converting the accumulated `f32`
+/// samples to `T` (via `from_f32`) on the way out.
+fn build_typed_output_stream<T>(
+    device: &cpal::Device,
+    config: &cpal::StreamConfig,
+    mut resampler: Resampler,
+    mut jitter_buffer: JitterBuffer,
+    rx: Receiver<Vec<f32>>,
+    from_f32: fn(f32) -> T,
+) -> Result<Stream>
+where
+    T: cpal::SizedSample + Send + 'static,
+{
+    let mut scratch: Vec<f32> = Vec::new();
+
+    let output_data_fn = move |output: &mut [T], _: &cpal::OutputCallbackInfo| {
+        loop {
+            match rx.try_recv() {
+                Ok(Some(frame)) => jitter_buffer.produce(resampler.process(&frame)),
+                Ok(None) => break,
+                Err(e) => {
+                    eprintln!("Error audio_output: {:?}", e);
+                    break;
                 }
-                None => (),
-            },
-            Err(e) => eprintln!("Error audio_output: {:?}", e),
-        };
+            }
+        }
+
+        scratch.clear();
+        scratch.resize(output.len(), 0.0);
+        if !jitter_buffer.consume_exact(&mut scratch) {
+            eprintln!(
+                "Warning: audio_output underrun, have {} samples, need {}",
+                jitter_buffer.samples_available(),
+                output.len()
+            );
+        }
+
+        for (out_sample, &f) in output.iter_mut().zip(scratch.iter()) {
+            *out_sample = from_f32(f);
+        }
+    };
+
+    Ok(device.build_output_stream(config, output_data_fn, err_fn, None)?)
+}
+
+fn audio_output(running: Arc<AtomicBool>, rx: Receiver<Vec<f32>>) -> Result<()> {
+    let (_input, output) = setup_host()?;
+    let resampler = Resampler::new(OPUS_SAMPLE_RATE as u32, output.config.sample_rate.0);
+    let jitter_buffer = JitterBuffer::new();
+
+    let stream = match output.sample_format {
+        cpal::SampleFormat::I8 => build_typed_output_stream::<i8>(
+            &output.device,
+            &output.config,
+            resampler,
+            jitter_buffer,
+            rx,
+            |s| (s.clamp(-1.0, 1.0) * i8::MAX as f32) as i8,
+        )?,
+        cpal::SampleFormat::I16 => build_typed_output_stream::<i16>(
+            &output.device,
+            &output.config,
+            resampler,
+            jitter_buffer,
+            rx,
+            |s| (s.clamp(-1.0, 1.0) * i16::MAX as f32) as i16,
+        )?,
+        cpal::SampleFormat::I32 => build_typed_output_stream::<i32>(
+            &output.device,
+            &output.config,
+            resampler,
+            jitter_buffer,
+            rx,
+            |s| (s.clamp(-1.0, 1.0) * i32::MAX as f32) as i32,
+        )?,
+        cpal::SampleFormat::F32 => build_typed_output_stream::<f32>(
+            &output.device,
+            &output.config,
+            resampler,
+            jitter_buffer,
+            rx,
+            |s| s,
+        )?,
+        other => {
+            return Err(AudioError::StreamConfigError(format!(
+                "unsupported output sample format: {:?}",
+                other
+            ))
+            .into())
+        }
     };
-    let stream = output_device.build_output_stream(&config, output_data_fn, err_fn, None)?;
     stream.play()?;
 
     while running.load(Ordering::Relaxed) {
@@ -232,47 +643,130 @@ fn audio_output(running: Arc<AtomicBool>, rx: Receiver<Vec<f32>>) -> Result<()>
     Ok(())
 }
 
+/// Size in bytes of the sequence-number header prepended to every packet
+/// handed to the network/decode stage. Lets `decode_audio` detect gaps
+/// caused by packet loss, and lets `transport` dedup/reorder on the same
+/// sequence number instead of inventing its own key.
+pub(crate) const SEQ_HEADER_LEN: usize = 4;
+
+/// Above this many consecutive missing sequence numbers we assume a stream
+/// reset (e.g. restart) rather than loss, and skip PLC padding instead of
+/// synthesizing a long run of concealment frames.
+const MAX_CONCEALED_GAP: u32 = 64;
+
 fn encode_audio(
     running: Arc<AtomicBool>,
     rx: Receiver<Vec<f32>>,
     tx: Sender<Vec<u8>>,
     frame_size: i32,
 ) -> Result<()> {
-    let encoder = SafeOpusEncoder::new(48000, 1)?;
+    let encoder = SafeOpusEncoder::new(OPUS_SAMPLE_RATE, 1)?;
+    encoder.configure(
+        &OpusEncoderConfig::from_env_args()
+            .with_inband_fec(true)
+            .with_packet_loss_perc(10),
+    )?;
+    eprintln!(
+        "encode_audio: bitrate={:?} vbr={:?} complexity={:?} inband_fec={:?} packet_loss_perc={:?}",
+        encoder.bitrate(),
+        encoder.vbr(),
+        encoder.complexity(),
+        encoder.inband_fec(),
+        encoder.packet_loss_perc(),
+    );
     let mut opus_buffer = vec![0u8; MAX_PACKET_SIZE];
+    let mut seq: u32 = 0;
 
     while running.load(Ordering::Relaxed) {
         match rx.try_recv() {
-            Ok(val) => {
-                match val {
-                    Some(data) => {
-                        let encoded_len = encoder.encode_float(&data, &mut opus_buffer, frame_size)?;
-                        tx.send(opus_buffer[..encoded_len as usize].to_vec())?;
-                    }
-                    None => (),
+            Ok(val) => match val {
+                Some(data) => {
+                    let encoded_len = encoder.encode_float(&data, &mut opus_buffer, frame_size)?;
+                    let mut packet = Vec::with_capacity(SEQ_HEADER_LEN + encoded_len as usize);
+                    packet.extend_from_slice(&seq.to_be_bytes());
+                    packet.extend_from_slice(&opus_buffer[..encoded_len as usize]);
+                    tx.send(packet)?;
+                    seq = seq.wrapping_add(1);
                 }
-            }
+                None => (),
+            },
             Err(e) => eprintln!("Error encode_audio: {:?}", e),
         }
     }
     Ok(())
 }
 
+fn decode_and_send(
+    decoder: &SafeOpusDecoder,
+    opus_buffer: &[u8],
+    frame_size: i32,
+    decode_fec: bool,
+    tx: &Sender<Vec<f32>>,
+) -> Result<()> {
+    let mut pcm_out = vec![0.0; frame_size as usize];
+    let decoded_len = decoder.decode_float(opus_buffer, &mut pcm_out, frame_size, decode_fec)?;
+    tx.send(pcm_out[..decoded_len as usize].to_vec())?;
+    Ok(())
+}
+
+fn conceal_and_send(
+    decoder: &SafeOpusDecoder,
+    frame_size: i32,
+    tx: &Sender<Vec<f32>>,
+) -> Result<()> {
+    let mut pcm_out = vec![0.0; frame_size as usize];
+    let decoded_len = decoder.decode_float_plc(&mut pcm_out, frame_size)?;
+    tx.send(pcm_out[..decoded_len as usize].to_vec())?;
+    Ok(())
+}
+
 fn decode_audio(
     running: Arc<AtomicBool>,
     rx: Receiver<Vec<u8>>,
     tx: Sender<Vec<f32>>,
     frame_size: i32,
 ) -> Result<()> {
-    let decoder = SafeOpusDecoder::new(48000, 1)?;
+    let decoder = SafeOpusDecoder::new(OPUS_SAMPLE_RATE, 1)?;
+    // Most recently received (seq, packet) pair, held back one packet so we
+    // know whether the *next* arrival skipped a sequence number before we
+    // commit to decoding it.
+    let mut pending: Option<(u32, Vec<u8>)> = None;
 
     while running.load(Ordering::Relaxed) {
         match rx.try_recv() {
             Ok(val) => match val {
-                Some(data) => {
-                    let mut pcm_out = vec![0.0; frame_size as usize];
-                    let decoded_len = decoder.decode_float(&data, &mut pcm_out, frame_size)?;
-                    tx.send(pcm_out[..decoded_len as usize].to_vec())?;
+                Some(packet) => {
+                    if packet.len() < SEQ_HEADER_LEN {
+                        eprintln!("Error decode_audio: packet shorter than sequence header");
+                        continue;
+                    }
+                    let seq = u32::from_be_bytes(packet[..SEQ_HEADER_LEN].try_into().unwrap());
+                    let data = packet[SEQ_HEADER_LEN..].to_vec();
+
+                    if let Some((last_seq, last_data)) = pending.take() {
+                        decode_and_send(&decoder, &last_data, frame_size, false, &tx)?;
+
+                        let gap = seq.wrapping_sub(last_seq).wrapping_sub(1);
+                        if gap > 0 && gap <= MAX_CONCEALED_GAP {
+                            // Iterate `gap` times via `wrapping_add` rather than
+                            // a `Range<u32>`: if `seq` has wrapped past
+                            // `u32::MAX`, `last_seq.wrapping_add(1) > seq`
+                            // numerically and a plain range would be empty
+                            // even though `gap` says frames are missing.
+                            let mut missing_seq = last_seq.wrapping_add(1);
+                            for _ in 0..gap {
+                                if missing_seq == seq.wrapping_sub(1) {
+                                    // Frame immediately before this packet: ask
+                                    // Opus to reconstruct it from inband FEC.
+                                    decode_and_send(&decoder, &data, frame_size, true, &tx)?;
+                                } else {
+                                    conceal_and_send(&decoder, frame_size, &tx)?;
+                                }
+                                missing_seq = missing_seq.wrapping_add(1);
+                            }
+                        }
+                    }
+                    pending = Some((seq, data));
                 }
                 None => (),
             },
@@ -282,16 +776,35 @@ fn decode_audio(
     Ok(())
 }
 
+/// Local-loopback transport: just forward encoded packets straight to the
+/// decoder, the way this app behaved before the UDP transport existed. Kept
+/// around as a `--mirror` mode for testing the rest of the pipeline without
+/// a second peer.
+fn mirror_loop(running: Arc<AtomicBool>, rx: Receiver<Vec<u8>>, tx: Sender<Vec<u8>>) -> Result<()> {
+    while running.load(Ordering::Relaxed) {
+        match rx.try_recv() {
+            Ok(Some(packet)) => tx.send(packet)?,
+            Ok(None) => thread::sleep(Duration::from_millis(1)),
+            Err(e) => eprintln!("Error mirror_loop: {:?}", e),
+        }
+    }
+    Ok(())
+}
+
 fn main() -> Result<()> {
     let (input_tx, input_rx) = bounded(16);
     let (encoder_tx, encoder_rx) = bounded(16);
+    let (network_tx, network_rx) = bounded(16);
     let (decoder_tx, decoder_rx) = bounded(16);
 
     let running = Arc::new(AtomicBool::new(true));
     let running_ctrlc = running.clone();
 
-    // Get frame_size from host setup
-    let (_input_device, _output_device, _config, frame_size) = setup_host()?;
+    // Opus always runs at OPUS_SAMPLE_RATE; device-rate audio is resampled
+    // to/from it in audio_input/audio_output.
+    let frame_size = OPUS_FRAME_SIZE;
+
+    let transport_mode = TransportMode::from_env_args()?;
 
     // Set up Ctrl+C handler
     ctrlc::set_handler(move || {
@@ -299,10 +812,10 @@ fn main() -> Result<()> {
         running_ctrlc.store(false, Ordering::Relaxed);
     })?;
 
-    let handles = vec![
+    let mut handles = vec![
         {
             let running = running.clone();
-            thread::spawn(move || audio_input(running, input_tx))
+            thread::spawn(move || audio_input(running, input_tx, frame_size))
         },
         {
             let running = running.clone();
@@ -310,7 +823,7 @@ fn main() -> Result<()> {
         },
         {
             let running = running.clone();
-            thread::spawn(move || decode_audio(running, encoder_rx, decoder_tx, frame_size))
+            thread::spawn(move || decode_audio(running, network_rx, decoder_tx, frame_size))
         },
         {
             let running = running.clone();
@@ -318,6 +831,26 @@ fn main() -> Result<()> {
         },
     ];
 
+    match transport_mode {
+        TransportMode::Mirror => {
+            println!("Transport: mirror (local loopback)");
+            let running = running.clone();
+            handles.push(thread::spawn(move || {
+                mirror_loop(running, encoder_rx, network_tx)
+            }));
+        }
+        TransportMode::Udp {
+            listen_addr,
+            remote_addr,
+        } => {
+            println!("Transport: UDP listening on {listen_addr}, peer {remote_addr}");
+            let running = running.clone();
+            handles.push(thread::spawn(move || {
+                transport::run_udp(running, listen_addr, remote_addr, encoder_rx, network_tx)
+            }));
+        }
+    }
+
     for handle in handles {
         handle.join().unwrap()?;
     }
@@ -0,0 +1,122 @@
+//! Streaming sample-rate conversion between a device's native rate and
+//! Opus' fixed operating rate.
+//!
+//! Opus only accepts 8/12/16/24/48 kHz, while cpal devices commonly default
+//! to 44.1 kHz. `Resampler` carries its fractional read position and the
+//! last sample of the previous call across invocations, so successive
+//! capture/playback callbacks interpolate smoothly instead of clicking at
+//! block boundaries.
+
+/// Linear-interpolation resampler between two fixed sample rates.
+pub struct Resampler {
+    input_rate: u32,
+    output_rate: u32,
+    /// Global input-sample coordinate (in input-rate units) of the next
+    /// output sample to produce.
+    next_global: f64,
+    /// Number of real input samples consumed across all calls so far;
+    /// together with `next_global` this anchors the fractional position
+    /// without needing to keep the whole history around.
+    consumed: u64,
+    /// Last sample of the previous chunk, used as the left-hand side of the
+    /// interpolation for the first output sample of the next chunk.
+    last_sample: f32,
+}
+
+impl Resampler {
+    pub fn new(input_rate: u32, output_rate: u32) -> Self {
+        Resampler {
+            input_rate,
+            output_rate,
+            next_global: 0.0,
+            consumed: 0,
+            last_sample: 0.0,
+        }
+    }
+
+    /// Resample one block of input, returning the corresponding block of
+    /// output samples. The input and output blocks need not be the same
+    /// length, and calls may be made with arbitrarily sized chunks.
+    pub fn process(&mut self, input: &[f32]) -> Vec<f32> {
+        if self.input_rate == self.output_rate {
+            return input.to_vec();
+        }
+        if input.is_empty() {
+            return Vec::new();
+        }
+
+        let step = self.input_rate as f64 / self.output_rate as f64;
+
+        // `combined[0]` is the carried-over last sample of the previous
+        // chunk, `combined[i + 1]` is `input[i]`.
+        let mut combined = Vec::with_capacity(input.len() + 1);
+        combined.push(self.last_sample);
+        combined.extend_from_slice(input);
+
+        let chunk_start = self.consumed as f64 - 1.0;
+        let mut global = self.next_global;
+        let mut out = Vec::new();
+
+        loop {
+            let combined_pos = global - chunk_start;
+            let idx = combined_pos.floor();
+            if idx < 0.0 || (idx as usize) + 1 >= combined.len() {
+                break;
+            }
+            let idx = idx as usize;
+            let frac = (combined_pos - idx as f64) as f32;
+            out.push(combined[idx] * (1.0 - frac) + combined[idx + 1] * frac);
+            global += step;
+        }
+
+        self.next_global = global;
+        self.consumed += input.len() as u64;
+        self.last_sample = *input.last().unwrap();
+        out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn same_rate_is_passthrough() {
+        let mut resampler = Resampler::new(48_000, 48_000);
+        let input = vec![0.1, 0.2, 0.3];
+        assert_eq!(resampler.process(&input), input);
+    }
+
+    #[test]
+    fn empty_input_returns_empty_output() {
+        let mut resampler = Resampler::new(44_100, 48_000);
+        assert!(resampler.process(&[]).is_empty());
+    }
+
+    #[test]
+    fn downsampling_halves_the_sample_count() {
+        let mut resampler = Resampler::new(48_000, 24_000);
+        let input: Vec<f32> = (0..100).map(|i| i as f32).collect();
+        let out = resampler.process(&input);
+        assert!((out.len() as i64 - 50).abs() <= 1);
+    }
+
+    #[test]
+    fn upsampling_doubles_the_sample_count() {
+        let mut resampler = Resampler::new(24_000, 48_000);
+        let input: Vec<f32> = (0..50).map(|i| i as f32).collect();
+        let out = resampler.process(&input);
+        assert!((out.len() as i64 - 100).abs() <= 1);
+    }
+
+    #[test]
+    fn state_carries_across_calls_without_clicking() {
+        let mut resampler = Resampler::new(44_100, 48_000);
+        let first = resampler.process(&[1.0, 1.0, 1.0, 1.0]);
+        let second = resampler.process(&[1.0, 1.0, 1.0, 1.0]);
+        assert!(first
+            .iter()
+            .chain(second.iter())
+            .all(|&s| (s - 1.0).abs() < 1e-6));
+    }
+}
@@ -0,0 +1,271 @@
+//! UDP transport so two peers can exchange Opus packets over the network
+//! instead of only ever decoding what they just encoded, plus a loopback
+//! "mirror" mode that keeps the old same-machine behavior for testing.
+
+use crate::SEQ_HEADER_LEN;
+use anyhow::{Context, Result};
+use kanal::{Receiver, Sender};
+use std::collections::{BTreeMap, VecDeque};
+use std::net::{SocketAddr, UdpSocket};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// Bytes of the RTP-like millisecond timestamp prepended to every UDP
+/// datagram, ahead of the sequence-numbered Opus packet `encode_audio`
+/// already produced.
+const TIMESTAMP_HEADER_LEN: usize = 4;
+
+/// How many recent sequence numbers the receive side remembers, so a
+/// datagram delivered more than once by the network is only forwarded once.
+const DEDUP_WINDOW: usize = 64;
+
+/// How many datagrams (in order or not) the receive side will hold back,
+/// keyed by sequence number, before forcing the oldest one through. Bounds both
+/// the reordering latency and the memory a stalled/lost packet can pin.
+const REORDER_WINDOW: usize = 8;
+
+/// Where encoded packets should go: straight back to the local decoder
+/// (useful for exercising the rest of the pipeline without a second peer),
+/// or out over a UDP socket to a remote address.
+#[derive(Debug, Clone)]
+pub enum TransportMode {
+    Mirror,
+    Udp {
+        listen_addr: SocketAddr,
+        remote_addr: SocketAddr,
+    },
+}
+
+impl TransportMode {
+    /// Parse `--mirror`, or `--listen <addr> --remote <addr>`, from the
+    /// process' command-line arguments. Defaults to mirror mode so the
+    /// existing loopback behavior keeps working without any flags.
+    pub fn from_env_args() -> Result<Self> {
+        let args: Vec<String> = std::env::args().collect();
+
+        if args.iter().any(|a| a == "--mirror") {
+            return Ok(TransportMode::Mirror);
+        }
+
+        let listen = args
+            .iter()
+            .position(|a| a == "--listen")
+            .and_then(|i| args.get(i + 1));
+        let remote = args
+            .iter()
+            .position(|a| a == "--remote")
+            .and_then(|i| args.get(i + 1));
+
+        match (listen, remote) {
+            (Some(listen), Some(remote)) => Ok(TransportMode::Udp {
+                listen_addr: listen.parse().context("invalid --listen address")?,
+                remote_addr: remote.parse().context("invalid --remote address")?,
+            }),
+            _ => Ok(TransportMode::Mirror),
+        }
+    }
+}
+
+fn now_millis() -> u32 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_millis() as u32
+}
+
+/// Send every encoded packet handed to `rx` out over `socket` to
+/// `remote_addr`, each prefixed with a millisecond timestamp.
+fn send_loop(
+    running: Arc<AtomicBool>,
+    rx: Receiver<Vec<u8>>,
+    socket: &UdpSocket,
+    remote_addr: SocketAddr,
+) -> Result<()> {
+    while running.load(Ordering::Relaxed) {
+        match rx.try_recv() {
+            Ok(Some(packet)) => {
+                let mut datagram = Vec::with_capacity(TIMESTAMP_HEADER_LEN + packet.len());
+                datagram.extend_from_slice(&now_millis().to_be_bytes());
+                datagram.extend_from_slice(&packet);
+                if let Err(e) = socket.send_to(&datagram, remote_addr) {
+                    eprintln!("Error transport send: {:?}", e);
+                }
+            }
+            Ok(None) => thread::sleep(Duration::from_millis(1)),
+            Err(e) => eprintln!("Error transport send channel: {:?}", e),
+        }
+    }
+    Ok(())
+}
+
+/// Dedup a newly-arrived `(seq, payload)` against `seen`, buffer it in
+/// `pending`, and return whatever datagrams are now ready to forward, in
+/// ascending sequence order. A duplicate `seq` is dropped (empty result).
+/// Kept free of the socket/channel so this exact buffering logic — the
+/// thing that shipped a real bug once already, when it deduped on
+/// timestamp instead of sequence number — can be unit tested directly.
+fn reorder_dedup(
+    seq: u32,
+    payload: Vec<u8>,
+    seen: &mut VecDeque<u32>,
+    pending: &mut BTreeMap<u32, Vec<u8>>,
+) -> Vec<(u32, Vec<u8>)> {
+    if seen.contains(&seq) {
+        return Vec::new();
+    }
+    if seen.len() == DEDUP_WINDOW {
+        seen.pop_front();
+    }
+    seen.push_back(seq);
+    pending.insert(seq, payload);
+
+    let mut ready = Vec::new();
+    while pending.len() > REORDER_WINDOW {
+        let oldest_seq = *pending.keys().next().unwrap();
+        ready.push((oldest_seq, pending.remove(&oldest_seq).unwrap()));
+    }
+    ready
+}
+
+/// Drain whatever is left in `pending`, in ascending sequence order. Used
+/// to flush the reorder buffer once the receive loop stops running.
+fn flush_pending(pending: &mut BTreeMap<u32, Vec<u8>>) -> Vec<(u32, Vec<u8>)> {
+    let mut ready = Vec::new();
+    while let Some((&oldest_seq, _)) = pending.iter().next() {
+        ready.push((oldest_seq, pending.remove(&oldest_seq).unwrap()));
+    }
+    ready
+}
+
+/// Receive datagrams from `socket` and hand the seq-numbered Opus packet to
+/// `tx` for `decode_audio` in ascending sequence order rather than raw
+/// arrival order. Both the dedup key and the reordering key are the 4-byte
+/// sequence number `encode_audio` prepends (the same one `decode_audio`
+/// uses for its own FEC/PLC gap detection), not the per-datagram send
+/// timestamp, since two distinct packets can legitimately land in the same
+/// millisecond.
+///
+/// Every datagram is held in `pending`, keyed by sequence number, and the
+/// lowest one is released once more than `REORDER_WINDOW` are buffered.
+/// Without this, a late-but-already-FEC-recovered packet would reach
+/// `decode_audio` after its concealment guess, producing the same frame
+/// twice.
+fn recv_loop(running: Arc<AtomicBool>, socket: &UdpSocket, tx: Sender<Vec<u8>>) -> Result<()> {
+    let mut buf = [0u8; 2048];
+    let mut seen: VecDeque<u32> = VecDeque::with_capacity(DEDUP_WINDOW);
+    let mut pending: BTreeMap<u32, Vec<u8>> = BTreeMap::new();
+
+    while running.load(Ordering::Relaxed) {
+        match socket.recv(&mut buf) {
+            Ok(len) => {
+                if len < TIMESTAMP_HEADER_LEN + SEQ_HEADER_LEN {
+                    eprintln!("Error transport recv: datagram shorter than header");
+                    continue;
+                }
+                let payload = buf[TIMESTAMP_HEADER_LEN..len].to_vec();
+                let seq = u32::from_be_bytes(payload[..SEQ_HEADER_LEN].try_into().unwrap());
+
+                for (_, packet) in reorder_dedup(seq, payload, &mut seen, &mut pending) {
+                    if let Err(e) = tx.send(packet) {
+                        eprintln!("Error transport recv channel: {:?}", e);
+                    }
+                }
+            }
+            Err(e) if e.kind() == std::io::ErrorKind::WouldBlock => {
+                thread::sleep(Duration::from_millis(1));
+            }
+            Err(e) => eprintln!("Error transport recv: {:?}", e),
+        }
+    }
+
+    for (_, packet) in flush_pending(&mut pending) {
+        if let Err(e) = tx.send(packet) {
+            eprintln!("Error transport recv channel: {:?}", e);
+        }
+    }
+    Ok(())
+}
+
+/// Bridge `outgoing` (packets from `encode_audio`) and `incoming` (packets
+/// for `decode_audio`) over a UDP socket bound to `listen_addr` and talking
+/// to `remote_addr`. Runs the send direction on its own thread and the
+/// receive direction on the calling thread, until `running` is cleared.
+pub fn run_udp(
+    running: Arc<AtomicBool>,
+    listen_addr: SocketAddr,
+    remote_addr: SocketAddr,
+    outgoing: Receiver<Vec<u8>>,
+    incoming: Sender<Vec<u8>>,
+) -> Result<()> {
+    let socket = UdpSocket::bind(listen_addr).context("Failed to bind UDP transport socket")?;
+    socket
+        .set_nonblocking(true)
+        .context("Failed to set UDP socket non-blocking")?;
+
+    let send_socket = socket.try_clone().context("Failed to clone UDP socket")?;
+    let send_running = running.clone();
+    let sender =
+        thread::spawn(move || send_loop(send_running, outgoing, &send_socket, remote_addr));
+
+    recv_loop(running, &socket, incoming)?;
+    sender.join().unwrap()?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn duplicate_sequence_number_is_dropped() {
+        let mut seen = VecDeque::new();
+        let mut pending = BTreeMap::new();
+
+        assert_eq!(
+            reorder_dedup(1, vec![1], &mut seen, &mut pending),
+            Vec::new()
+        );
+        assert_eq!(
+            reorder_dedup(1, vec![1], &mut seen, &mut pending),
+            Vec::new()
+        );
+        assert_eq!(pending.len(), 1);
+    }
+
+    #[test]
+    fn out_of_order_arrivals_are_released_in_sequence_order() {
+        let mut seen = VecDeque::new();
+        let mut pending = BTreeMap::new();
+
+        // Arrive as 0, 2, 3, 1 — nothing is released until REORDER_WINDOW
+        // is exceeded, and then it comes out in ascending seq order.
+        for seq in [0u32, 2, 3] {
+            assert!(reorder_dedup(seq, vec![seq as u8], &mut seen, &mut pending).is_empty());
+        }
+        for seq in 4..=REORDER_WINDOW as u32 {
+            assert!(reorder_dedup(seq, vec![seq as u8], &mut seen, &mut pending).is_empty());
+        }
+
+        // The buffer now holds REORDER_WINDOW packets (0, 2, 3, 4..=REORDER_WINDOW);
+        // one more forces the oldest (0) out.
+        let released = reorder_dedup(1, vec![1], &mut seen, &mut pending);
+        assert_eq!(released, vec![(0, vec![0])]);
+    }
+
+    #[test]
+    fn flush_pending_drains_in_ascending_order() {
+        let mut pending = BTreeMap::new();
+        pending.insert(5u32, vec![5u8]);
+        pending.insert(2u32, vec![2u8]);
+        pending.insert(3u32, vec![3u8]);
+
+        let flushed = flush_pending(&mut pending);
+        assert_eq!(
+            flushed,
+            vec![(2, vec![2u8]), (3, vec![3u8]), (5, vec![5u8])]
+        );
+        assert!(pending.is_empty());
+    }
+}